@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// Assertions (and the tokens minted from them) are valid for one hour.
+const TOKEN_TTL_SECS: u64 = 3600;
+/// Refresh this many seconds before the cached token actually expires so an
+/// in-flight request never races the clock.
+const REFRESH_SKEW_SECS: u64 = 60;
+
+/// A minted bearer token plus the wall-clock second it stops being usable.
+#[derive(Clone)]
+struct CachedToken {
+    token: String,
+    expires_at: u64,
+}
+
+impl CachedToken {
+    fn is_fresh(&self, now: u64) -> bool {
+        now + REFRESH_SKEW_SECS < self.expires_at
+    }
+}
+
+static TOKEN_CACHE: Lazy<std::sync::RwLock<HashMap<String, CachedToken>>> =
+    Lazy::new(|| std::sync::RwLock::new(HashMap::new()));
+
+/// Per-client refresh locks: N concurrent requests for the same client funnel
+/// through one lock so only a single JWT exchange happens per expiry window.
+static REFRESH_LOCKS: Lazy<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+/// The relevant fields of an Application Default Credentials service-account
+/// key file.
+#[derive(Debug, Deserialize)]
+struct AdcServiceAccount {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct JwtClaims {
+    iss: String,
+    scope: String,
+    aud: String,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs()
+}
+
+/// Return a valid OAuth2 bearer token for `client_name`, minting and caching a
+/// fresh one only when the previous token is missing or about to expire.
+pub async fn get_access_token(client_name: &str) -> anyhow::Result<String> {
+    if let Some(cached) = read_cached(client_name) {
+        if cached.is_fresh(now_secs()) {
+            return Ok(cached.token);
+        }
+    }
+
+    let lock = refresh_lock(client_name);
+    let _guard = lock.lock().await;
+
+    // Another task may have refreshed while we waited for the lock.
+    if let Some(cached) = read_cached(client_name) {
+        if cached.is_fresh(now_secs()) {
+            return Ok(cached.token);
+        }
+    }
+
+    let token = mint_token(client_name).await?;
+    TOKEN_CACHE
+        .write()
+        .expect("token cache lock poisoned")
+        .insert(client_name.to_string(), token.clone());
+    Ok(token.token)
+}
+
+fn read_cached(client_name: &str) -> Option<CachedToken> {
+    TOKEN_CACHE
+        .read()
+        .expect("token cache lock poisoned")
+        .get(client_name)
+        .cloned()
+}
+
+fn refresh_lock(client_name: &str) -> Arc<Mutex<()>> {
+    let mut locks = REFRESH_LOCKS.lock().expect("refresh lock map poisoned");
+    locks
+        .entry(client_name.to_string())
+        .or_insert_with(|| Arc::new(Mutex::new(())))
+        .clone()
+}
+
+/// Load the ADC key, sign a JWT assertion and exchange it for a bearer token.
+async fn mint_token(client_name: &str) -> anyhow::Result<CachedToken> {
+    let account = load_adc(client_name)?;
+    let iat = now_secs();
+    let exp = iat + TOKEN_TTL_SECS;
+    let claims = JwtClaims {
+        iss: account.client_email.clone(),
+        scope: SCOPE.to_string(),
+        aud: account.token_uri.clone(),
+        iat,
+        exp,
+    };
+
+    // RS256 signing is CPU-bound; keep it off the async runtime's worker thread.
+    let private_key = account.private_key.clone();
+    let assertion = tokio::task::spawn_blocking(move || sign_assertion(&claims, &private_key))
+        .await
+        .context("jwt signing task panicked")??;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&account.token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &assertion),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    let token: TokenResponse = response.json().await?;
+
+    Ok(CachedToken {
+        token: token.access_token,
+        expires_at: now_secs() + token.expires_in,
+    })
+}
+
+fn sign_assertion(claims: &JwtClaims, private_key: &str) -> anyhow::Result<String> {
+    let header = Header::new(Algorithm::RS256);
+    let key = EncodingKey::from_rsa_pem(private_key.as_bytes())
+        .context("ADC private_key is not a valid RSA PEM")?;
+    Ok(encode(&header, claims, &key)?)
+}
+
+/// Resolve the ADC JSON path for `client_name` and parse it. A per-client env
+/// var (`ADC_<CLIENT_NAME>`) wins over the conventional
+/// `GOOGLE_APPLICATION_CREDENTIALS` fallback.
+fn load_adc(client_name: &str) -> anyhow::Result<AdcServiceAccount> {
+    let per_client = format!("ADC_{}", client_name.to_uppercase());
+    let path = std::env::var(&per_client)
+        .or_else(|_| std::env::var("GOOGLE_APPLICATION_CREDENTIALS"))
+        .with_context(|| format!("no ADC path set ({per_client} or GOOGLE_APPLICATION_CREDENTIALS)"))?;
+    let raw = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read ADC file at {path}"))?;
+    let account: AdcServiceAccount = serde_json::from_str(&raw)?;
+    Ok(account)
+}
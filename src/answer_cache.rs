@@ -0,0 +1,156 @@
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use tokio::sync::RwLock;
+
+use crate::TableContent;
+
+const DEFAULT_CAPACITY: usize = 128;
+const DEFAULT_TTL_SECS: u64 = 3600;
+
+/// A cached completion and the instant it stops being valid.
+struct CacheEntry {
+    completion: String,
+    expires_at: Instant,
+}
+
+/// Bounded LRU of LLM answers with a per-entry TTL. Evicts by least-recently
+/// used once capacity is reached and treats expired entries as misses on read.
+pub struct AnswerCache {
+    entries: LruCache<String, CacheEntry>,
+    ttl: Duration,
+}
+
+impl AnswerCache {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: LruCache::new(capacity),
+            ttl,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<String> {
+        match self.entries.peek(key) {
+            Some(entry) if entry.expires_at > Instant::now() => {
+                // Promote to most-recently-used on a live hit.
+                self.entries.get(key).map(|e| e.completion.clone())
+            }
+            Some(_) => {
+                self.entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: String, completion: String) {
+        self.entries.put(
+            key,
+            CacheEntry {
+                completion,
+                expires_at: Instant::now() + self.ttl,
+            },
+        );
+    }
+}
+
+static ANSWER_CACHE: Lazy<Arc<RwLock<AnswerCache>>> = Lazy::new(|| {
+    let capacity = env_usize("ANSWER_CACHE_CAPACITY", DEFAULT_CAPACITY);
+    let ttl = Duration::from_secs(env_u64("ANSWER_CACHE_TTL_SECS", DEFAULT_TTL_SECS));
+    Arc::new(RwLock::new(AnswerCache::new(capacity, ttl)))
+});
+
+fn env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Derive a stable cache key from the normalized query and the ordered table
+/// `content` blocks the retrieval step selected. Two requests that would feed
+/// the LLM the same prompt collapse onto the same key.
+pub fn cache_key(query: &str, tables: &[TableContent]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(query.trim().to_lowercase().as_bytes());
+    for table in tables {
+        hasher.update(b"\n");
+        hasher.update(table.content.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Return a cached completion for `key` if one is present and unexpired.
+pub async fn get(key: &str) -> Option<String> {
+    ANSWER_CACHE.write().await.get(key)
+}
+
+/// Store `completion` under `key`, evicting the least-recently-used entry if
+/// the cache is at capacity.
+pub async fn insert(key: String, completion: String) {
+    ANSWER_CACHE.write().await.insert(key, completion);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(content: &str) -> TableContent {
+        TableContent {
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn live_entry_is_a_hit_and_expired_entry_is_a_miss() {
+        let mut cache = AnswerCache::new(4, Duration::from_secs(60));
+        cache.insert("k".to_string(), "answer".to_string());
+        assert_eq!(cache.get("k"), Some("answer".to_string()));
+
+        // A zero TTL expires the entry immediately, so the next read misses and
+        // the stale entry is dropped.
+        let mut expired = AnswerCache::new(4, Duration::from_secs(0));
+        expired.insert("k".to_string(), "answer".to_string());
+        assert_eq!(expired.get("k"), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_at_capacity() {
+        let mut cache = AnswerCache::new(2, Duration::from_secs(60));
+        cache.insert("a".to_string(), "1".to_string());
+        cache.insert("b".to_string(), "2".to_string());
+        // Touch "a" so "b" becomes the least-recently-used victim.
+        assert_eq!(cache.get("a"), Some("1".to_string()));
+        cache.insert("c".to_string(), "3".to_string());
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some("1".to_string()));
+        assert_eq!(cache.get("c"), Some("3".to_string()));
+    }
+
+    #[test]
+    fn cache_key_is_stable_and_order_and_query_sensitive() {
+        let tables = [table("orders"), table("customers")];
+        // Normalization: surrounding whitespace and case don't change the key.
+        assert_eq!(
+            cache_key("  Schema? ", &tables),
+            cache_key("schema?", &tables)
+        );
+        // Table ordering is part of the key.
+        let reordered = [table("customers"), table("orders")];
+        assert_ne!(cache_key("schema?", &tables), cache_key("schema?", &reordered));
+        // A different query yields a different key.
+        assert_ne!(cache_key("schema?", &tables), cache_key("other?", &tables));
+    }
+}
@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::llm_utils::LLMRequest;
+
+/// Knobs shared by every backend. These used to live as builder fields on
+/// `LLMRequest`; lifting them out keeps the call sites provider-agnostic.
+pub struct CompletionOpts {
+    pub max_tokens: u32,
+    pub temperature: f32,
+}
+
+impl Default for CompletionOpts {
+    fn default() -> Self {
+        Self {
+            max_tokens: 800,
+            temperature: 0.5,
+        }
+    }
+}
+
+/// A chat-completion backend. Each implementor owns the quirks of its own
+/// wire format — request serialization and the path the answer is dug out of —
+/// so the RAG pipeline can stay ignorant of which model is actually answering.
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    async fn complete(
+        &self,
+        msgs: &[(String, String)],
+        opts: &CompletionOpts,
+    ) -> anyhow::Result<String>;
+}
+
+/// OpenAI/Azure backend. Delegates to the existing `LLMRequest` transport so
+/// the header/`choices[0].message.content` schema lives in one place.
+pub struct OpenAIClient {
+    pub api_url: String,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl LlmClient for OpenAIClient {
+    async fn complete(
+        &self,
+        msgs: &[(String, String)],
+        opts: &CompletionOpts,
+    ) -> anyhow::Result<String> {
+        let request = LLMRequest::builder()
+            .messages(msgs.to_vec())
+            .api_key(self.api_key.clone())
+            .api_url(self.api_url.clone())
+            .max_tokens(opts.max_tokens)
+            .temperature(opts.temperature)
+            .build();
+        request.send().await
+    }
+}
+
+/// Google Gemini (Generative Language API). Authenticates with a `?key=` query
+/// param and nests the answer under `candidates[0].content.parts[0].text`.
+pub struct GeminiClient {
+    pub api_url: String,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl LlmClient for GeminiClient {
+    async fn complete(
+        &self,
+        msgs: &[(String, String)],
+        opts: &CompletionOpts,
+    ) -> anyhow::Result<String> {
+        let body = gemini_request_body(msgs, opts);
+        let client = Client::new();
+        let response = client
+            .post(&self.api_url)
+            .query(&[("key", self.api_key.as_str())])
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let resp_text = response.text().await?;
+        extract_gemini_response(&resp_text)
+    }
+}
+
+/// Vertex AI. Same request/response shape as Gemini, but the endpoint is
+/// project/location scoped and authentication is a short-lived Bearer OAuth
+/// token rather than a static API key.
+pub struct VertexAIClient {
+    pub project: String,
+    pub location: String,
+    pub model: String,
+    /// Key under which this client's ADC credentials are resolved when minting
+    /// OAuth tokens.
+    pub client_name: String,
+}
+
+impl VertexAIClient {
+    fn endpoint(&self) -> String {
+        format!(
+            "https://{location}-aiplatform.googleapis.com/v1/projects/{project}/locations/{location}/publishers/google/models/{model}:generateContent",
+            location = self.location,
+            project = self.project,
+            model = self.model,
+        )
+    }
+}
+
+#[async_trait]
+impl LlmClient for VertexAIClient {
+    async fn complete(
+        &self,
+        msgs: &[(String, String)],
+        opts: &CompletionOpts,
+    ) -> anyhow::Result<String> {
+        let body = gemini_request_body(msgs, opts);
+        let token = crate::access_token::get_access_token(&self.client_name).await?;
+        let client = Client::new();
+        let response = client
+            .post(self.endpoint())
+            .bearer_auth(&token)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let resp_text = response.text().await?;
+        extract_gemini_response(&resp_text)
+    }
+}
+
+/// Build the `contents`/`generationConfig` body shared by Gemini and Vertex.
+/// The system turn becomes a `systemInstruction` and the `assistant` role is
+/// renamed to Gemini's `model`.
+fn gemini_request_body(msgs: &[(String, String)], opts: &CompletionOpts) -> serde_json::Value {
+    let mut contents = Vec::new();
+    let mut system_instruction = None;
+    for (role, content) in msgs {
+        match role.as_str() {
+            "system" => system_instruction = Some(content.clone()),
+            other => {
+                let gemini_role = if other == "assistant" { "model" } else { "user" };
+                contents.push(json!({
+                    "role": gemini_role,
+                    "parts": [{ "text": content }],
+                }));
+            }
+        }
+    }
+
+    let mut body = json!({
+        "contents": contents,
+        "generationConfig": {
+            "maxOutputTokens": opts.max_tokens,
+            "temperature": opts.temperature,
+        },
+    });
+    if let Some(sys) = system_instruction {
+        body["systemInstruction"] = json!({ "parts": [{ "text": sys }] });
+    }
+    body
+}
+
+fn extract_gemini_response(response: &str) -> anyhow::Result<String> {
+    let resp_json: serde_json::Value = serde_json::from_str(response)?;
+    resp_json["candidates"][0]["content"]["parts"][0]["text"]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("unexpected Gemini response shape: {response}"))
+}
+
+/// Runtime backend selection, deserialized from config. The `type` tag chooses
+/// the variant so users can repoint the server without touching pipeline code.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientConfig {
+    Openai { api_url: String, api_key: String },
+    Gemini { api_url: String, api_key: String },
+    Vertexai {
+        project: String,
+        location: String,
+        model: String,
+        client_name: String,
+    },
+}
+
+impl ClientConfig {
+    pub fn build(self) -> Box<dyn LlmClient> {
+        match self {
+            ClientConfig::Openai { api_url, api_key } => {
+                Box::new(OpenAIClient { api_url, api_key })
+            }
+            ClientConfig::Gemini { api_url, api_key } => {
+                Box::new(GeminiClient { api_url, api_key })
+            }
+            ClientConfig::Vertexai {
+                project,
+                location,
+                model,
+                client_name,
+            } => Box::new(VertexAIClient {
+                project,
+                location,
+                model,
+                client_name,
+            }),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use axum::http::StatusCode;
+
+/// Failures from the outbound LLM layer that callers translate into HTTP
+/// responses instead of panicking. Transient throttling and upstream
+/// unavailability are kept distinct from genuine upstream error bodies.
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("local rate limit exceeded for client {client}")]
+    RateLimited { client: String },
+
+    #[error("upstream unavailable after {attempts} attempt(s)")]
+    ServiceUnavailable { attempts: u32 },
+
+    #[error("upstream error {status}: {message}")]
+    Upstream { status: u16, message: String },
+}
+
+impl LlmError {
+    /// The HTTP status a handler should report for this failure.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            LlmError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            LlmError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            // An error body that arrived with a success status must not be
+            // reported to the client as a success.
+            LlmError::Upstream { status, .. } => StatusCode::from_u16(*status)
+                .ok()
+                .filter(|s| s.is_client_error() || s.is_server_error())
+                .unwrap_or(StatusCode::BAD_GATEWAY),
+        }
+    }
+}
+
+/// Map an error returned by the pipeline onto an HTTP status, recognising a
+/// typed [`LlmError`] if one is carried and defaulting to 500 otherwise.
+pub fn status_for(error: &anyhow::Error) -> StatusCode {
+    error
+        .downcast_ref::<LlmError>()
+        .map(LlmError::status_code)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -1,7 +1,42 @@
+use std::collections::VecDeque;
+
+use futures::{Stream, StreamExt};
 use reqwest::Client;
+use serde::Deserialize;
 use serde_json::json;
 use typed_builder::TypedBuilder;
 
+use crate::llm_error::LlmError;
+
+/// The chat-completion response, which is either the success payload or the
+/// provider's error object. Ordered error-first so an error body doesn't get
+/// mis-parsed as an (empty) success.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApiResponse {
+    Error { error: ProviderError },
+    Success { choices: Vec<Choice> },
+}
+
+#[derive(Deserialize)]
+struct ProviderError {
+    message: String,
+    #[serde(default)]
+    code: Option<serde_json::Value>,
+    #[serde(rename = "type", default)]
+    error_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    content: String,
+}
+
 #[derive(TypedBuilder)]
 pub struct LLMRequest {
     messages: Vec<(String, String)>,
@@ -13,25 +48,275 @@ pub struct LLMRequest {
     api_key: String,
 }
 
+impl ProviderError {
+    /// Fold the provider's error object into a typed upstream error, tagging
+    /// the message with the error `type`/`code` when present.
+    fn into_upstream(self, status: u16) -> LlmError {
+        let detail = match (self.error_type, self.code) {
+            (Some(t), Some(c)) => format!("{t} ({c}): {}", self.message),
+            (Some(t), None) => format!("{t}: {}", self.message),
+            (None, Some(c)) => format!("({c}): {}", self.message),
+            (None, None) => self.message,
+        };
+        LlmError::Upstream {
+            status,
+            message: detail,
+        }
+    }
+}
+
+/// Build an upstream error for an error-status response, preferring the parsed
+/// provider error body and falling back to the raw text when it doesn't parse.
+fn provider_error(status: u16, body: &str) -> LlmError {
+    match serde_json::from_str::<ApiResponse>(body) {
+        Ok(ApiResponse::Error { error }) => error.into_upstream(status),
+        _ => LlmError::Upstream {
+            status,
+            message: body.trim().to_string(),
+        },
+    }
+}
+
+/// Parse a `Retry-After` header expressed in whole seconds into a delay.
+/// Absent or unparseable headers fall back to computed backoff.
+fn retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(std::time::Duration::from_secs)
+}
+
+/// The result of classifying one line of the streamed SSE body.
+enum StreamLine {
+    Token(String),
+    Done,
+    Skip,
+}
+
+/// Carries the in-flight byte stream plus the parsing scratch space across
+/// `unfold` iterations while `send_stream` is being consumed.
+struct StreamState<S> {
+    inner: S,
+    buf: Vec<u8>,
+    ready: VecDeque<String>,
+    done: bool,
+}
+
+/// How many attempts in all (the first call plus its retries) `send` and
+/// `send_stream` make against a throttled/unavailable upstream before giving
+/// up. Overridable via `LLM_MAX_RETRIES`.
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+
 impl LLMRequest {
     pub async fn send(&self) -> anyhow::Result<String> {
-        let json_request = self.get_json_request();
+        let json_request = self.get_json_request().to_string();
+        let resp_text = self.send_with_retry(&json_request).await?;
+        self.extract_response(&resp_text)
+    }
+
+    /// Issue the request through the rate limiter, retrying on `429`/`503` with
+    /// `Retry-After`-aware exponential backoff. Local throttling and exhausted
+    /// retries surface as a typed [`LlmError`](crate::llm_error::LlmError).
+    async fn send_with_retry(&self, body: &str) -> anyhow::Result<String> {
+        let client = Client::new();
+        let max_attempts = std::env::var("LLM_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            crate::rate_limit::acquire(&self.api_url).await?;
+
+            let response = client
+                .post(self.api_url.clone())
+                .header("Content-Type", "application/json")
+                .header("api-key", self.api_key.clone())
+                .body(body.to_string())
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response.text().await?);
+            }
+
+            let retryable = status.as_u16() == 429 || status.as_u16() == 503;
+            if retryable && attempt < max_attempts {
+                let delay = retry_after(&response)
+                    .unwrap_or_else(|| crate::rate_limit::backoff_delay(attempt));
+                tracing::warn!(
+                    "upstream returned {status}, retrying in {:?} (attempt {attempt}/{max_attempts})",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            if retryable {
+                return Err(crate::llm_error::LlmError::ServiceUnavailable {
+                    attempts: attempt,
+                }
+                .into());
+            }
+
+            // Non-retryable error status: surface the provider's own error
+            // message rather than masking it.
+            let text = response.text().await?;
+            return Err(provider_error(status.as_u16(), &text).into());
+        }
+    }
+
+    /// Issue the streaming request through the same rate limiter and
+    /// `Retry-After`-aware backoff as [`send_with_retry`](Self::send_with_retry),
+    /// returning the successful response whose body is then consumed as the SSE
+    /// stream. Retries happen before any tokens are yielded, so the caller only
+    /// ever streams a `2xx` response.
+    async fn send_stream_with_retry(&self, body: &str) -> anyhow::Result<reqwest::Response> {
         let client = Client::new();
-        let response = client
-            .post(self.api_url.clone())
-            .header("Content-Type", "application/json")
-            .header("api-key", self.api_key.clone())
-            .body(json_request.to_string())
-            .send()
-            .await?;
-
-        let resp_text = response.text().await?;
-        Ok(self.extract_response(resp_text))
+        let max_attempts = std::env::var("LLM_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            crate::rate_limit::acquire(&self.api_url).await?;
+
+            let response = client
+                .post(self.api_url.clone())
+                .header("Content-Type", "application/json")
+                .header("api-key", self.api_key.clone())
+                .body(body.to_string())
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let retryable = status.as_u16() == 429 || status.as_u16() == 503;
+            if retryable && attempt < max_attempts {
+                let delay = retry_after(&response)
+                    .unwrap_or_else(|| crate::rate_limit::backoff_delay(attempt));
+                tracing::warn!(
+                    "upstream returned {status}, retrying stream in {:?} (attempt {attempt}/{max_attempts})",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            if retryable {
+                return Err(crate::llm_error::LlmError::ServiceUnavailable {
+                    attempts: attempt,
+                }
+                .into());
+            }
+
+            let text = response.text().await?;
+            return Err(provider_error(status.as_u16(), &text).into());
+        }
     }
 
-    fn extract_response(&self, response: String) -> String {
-        let resp_json: serde_json::Value = serde_json::from_str(&response).unwrap();
-        resp_json["choices"][0]["message"]["content"].to_string()
+    /// Send the request in streaming mode and yield completion tokens as they
+    /// arrive. The upstream answers with a chunked `text/event-stream` body; we
+    /// accumulate the raw bytes, split them into `data:` lines (a single chunk
+    /// may carry several, or half of one), parse each line's JSON and hand back
+    /// `choices[0].delta.content`. The terminal `data: [DONE]` sentinel ends the
+    /// stream and keep-alive comment lines are ignored.
+    pub async fn send_stream(
+        &self,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<String>>> {
+        let mut json_request = self.get_json_request();
+        json_request["stream"] = json!(true);
+        let body = json_request.to_string();
+
+        let response = self.send_stream_with_retry(&body).await?;
+
+        let state = StreamState {
+            inner: Box::pin(response.bytes_stream()),
+            buf: Vec::new(),
+            ready: VecDeque::new(),
+            done: false,
+        };
+
+        Ok(futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(token) = state.ready.pop_front() {
+                    return Some((Ok(token), state));
+                }
+                if state.done {
+                    return None;
+                }
+                match state.inner.next().await {
+                    Some(Ok(bytes)) => {
+                        // Accumulate raw bytes; a multibyte character split across
+                        // a chunk boundary stays intact until the whole line lands.
+                        state.buf.extend_from_slice(&bytes);
+                        // Only drain complete lines; a trailing partial line is
+                        // left in `buf` until the rest of it arrives.
+                        while let Some(idx) = state.buf.iter().position(|&b| b == b'\n') {
+                            let line_bytes: Vec<u8> = state.buf.drain(..=idx).collect();
+                            let line = String::from_utf8_lossy(&line_bytes);
+                            let line = line.trim();
+                            match Self::parse_stream_line(line) {
+                                StreamLine::Token(t) => state.ready.push_back(t),
+                                StreamLine::Done => {
+                                    state.done = true;
+                                    break;
+                                }
+                                StreamLine::Skip => {}
+                            }
+                        }
+                    }
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(e.into()), state));
+                    }
+                    None => return None,
+                }
+            }
+        }))
+    }
+
+    /// Classify a single line of the SSE body. Blank lines and keep-alive
+    /// comments (lines starting with `:`) carry no payload and are skipped.
+    fn parse_stream_line(line: &str) -> StreamLine {
+        let Some(data) = line.strip_prefix("data:") else {
+            return StreamLine::Skip;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            return StreamLine::Done;
+        }
+        match serde_json::from_str::<serde_json::Value>(data) {
+            Ok(json) => match json["choices"][0]["delta"]["content"].as_str() {
+                Some(content) => StreamLine::Token(content.to_string()),
+                None => StreamLine::Skip,
+            },
+            Err(_) => StreamLine::Skip,
+        }
+    }
+
+    /// Parse a completion body, returning the first choice's content. A
+    /// malformed body or a success-status response that nonetheless carries an
+    /// `error` object becomes an error instead of the literal string `"null"`.
+    fn extract_response(&self, response: &str) -> anyhow::Result<String> {
+        match serde_json::from_str::<ApiResponse>(response)? {
+            ApiResponse::Success { choices } => choices
+                .into_iter()
+                .next()
+                .map(|c| c.message.content)
+                .ok_or_else(|| anyhow::anyhow!("response contained no choices: {response}")),
+            ApiResponse::Error { error } => Err(error.into_upstream(200).into()),
+        }
     }
 
     fn get_json_request(&self) -> serde_json::Value {
@@ -58,6 +343,38 @@ impl LLMRequest {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_stream_line_extracts_delta_content() {
+        let line = r#"data: {"choices":[{"delta":{"content":"Hello"}}]}"#;
+        match LLMRequest::parse_stream_line(line) {
+            StreamLine::Token(t) => assert_eq!(t, "Hello"),
+            _ => panic!("expected a token"),
+        }
+    }
+
+    #[test]
+    fn parse_stream_line_recognises_done_sentinel() {
+        assert!(matches!(
+            LLMRequest::parse_stream_line("data: [DONE]"),
+            StreamLine::Done
+        ));
+    }
+
+    #[test]
+    fn parse_stream_line_skips_comments_and_blank_and_role_deltas() {
+        // Keep-alive comment, blank line, and a role-only delta all carry no
+        // token payload.
+        assert!(matches!(
+            LLMRequest::parse_stream_line(": keep-alive"),
+            StreamLine::Skip
+        ));
+        assert!(matches!(LLMRequest::parse_stream_line(""), StreamLine::Skip));
+        assert!(matches!(
+            LLMRequest::parse_stream_line(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#),
+            StreamLine::Skip
+        ));
+    }
+
     #[test]
     fn test_get_json_request() {
         let messages = vec![("user".to_string(), "Hello, how are you?".to_string())];
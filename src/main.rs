@@ -1,11 +1,18 @@
 use std::sync::Arc;
 
+use anyhow::Context;
 use axum::{
     extract::rejection::LengthLimitError,
-    response::IntoResponse,
+    extract::Path,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     routing::{get, post},
     Json, Router,
 };
+use futures::{Stream, StreamExt};
+use llm_client::{ClientConfig, CompletionOpts, LlmClient};
 use llm_utils::LLMRequest;
 use once_cell::sync::{Lazy, OnceCell};
 use serde::Deserialize;
@@ -13,8 +20,15 @@ use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{info, Level};
 use vecdb::VecDB;
+mod access_token;
+mod answer_cache;
 mod embedder;
+mod llm_client;
+mod llm_error;
 mod llm_utils;
+mod rate_limit;
+mod threads;
+mod tokenizer;
 mod vecdb;
 mod vectors;
 
@@ -41,6 +55,8 @@ async fn main() -> anyhow::Result<()> {
 
     let app = Router::new()
         .route("/generate", post(generate_response))
+        .route("/threads", post(create_thread))
+        .route("/threads/:id/messages", post(post_thread_message))
         .route("/test", get(test_service))
         .layer(cors);
 
@@ -80,25 +96,202 @@ async fn test_service() -> impl IntoResponse {
 #[axum::debug_handler]
 async fn generate_response(body: String) -> impl IntoResponse {
     info!("recieved query: {body}");
-    let response = process_query(body).await;
-    match response {
-        Ok(response) => (StatusCode::OK, response),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
-    }
+    // A failure while building the request (retrieval, rate limit, config) is
+    // reported as a non-200 response before any streaming begins, so the client
+    // never mistakes an error for an answer.
+    let stream = match process_query_stream(body).await {
+        Ok(stream) => stream,
+        Err(e) => return (llm_error::status_for(&e), e.to_string()).into_response(),
+    };
+    // Errors raised mid-stream are delivered as a named `error` event rather
+    // than inlined as ordinary token data, so the browser can tell them apart.
+    let sse = stream.map(|item| match item {
+        Ok(token) => Ok::<_, std::convert::Infallible>(Event::default().data(token)),
+        Err(e) => Ok(Event::default().event("error").data(e.to_string())),
+    });
+    Sse::new(sse).keep_alive(KeepAlive::default()).into_response()
 }
 
-async fn process_query(query: String) -> anyhow::Result<String> {
+/// Run the retrieval pipeline and return the LLM completion as a token stream
+/// so the SSE handler can relay tokens to the browser as they are produced. Any
+/// error raised while building the request is returned to the caller up front.
+async fn process_query_stream(query: String) -> anyhow::Result<BoxTokenStream> {
     let embedding = embed_and_normalize_query(query.clone())?;
     tracing::info!("embedding generated");
     let context_tables = get_relevant_context_tables(embedding, 20).await?;
     tracing::info!("context tables generated");
-    let prompt_msgs = generate_prompt_msgs(query, context_tables).await?;
-    tracing::info!("prompt messages generated");
-    let response = generate_response_llm(prompt_msgs).await;
-    tracing::info!("response generated");
+
+    // Short-circuit repeat questions: a hit replays the stored completion as a
+    // single frame so the SSE contract is unchanged.
+    let cache_key = answer_cache::cache_key(&query, &context_tables);
+    if let Some(cached) = answer_cache::get(&cache_key).await {
+        tracing::info!("answer cache hit");
+        let hit = futures::stream::once(async move { Ok(cached) });
+        return Ok(Box::pin(hit) as BoxTokenStream);
+    }
+
+    let assembly = generate_prompt_msgs(query, context_tables).await?;
+    tracing::info!(
+        "prompt messages generated: {}/{} tables included",
+        assembly.included_tables,
+        assembly.candidate_tables
+    );
+    let max_tokens = completion_max_tokens(&assembly.messages);
+    let stream = generate_response_llm_stream(assembly.messages, max_tokens).await?;
+    // On a miss, tee the tokens through to the client while accumulating the
+    // full answer, then insert it into the cache once the stream completes.
+    Ok(cache_on_complete(cache_key, stream))
+}
+
+/// Wrap a token stream so the concatenated completion is written to the answer
+/// cache once the stream ends without error. Tokens are forwarded unchanged as
+/// they arrive; a stream that errors mid-flight is left uncached.
+fn cache_on_complete(key: String, stream: BoxTokenStream) -> BoxTokenStream {
+    struct CacheState {
+        inner: BoxTokenStream,
+        key: String,
+        acc: String,
+        failed: bool,
+    }
+
+    let state = CacheState {
+        inner: stream,
+        key,
+        acc: String::new(),
+        failed: false,
+    };
+
+    Box::pin(futures::stream::unfold(state, |mut state| async move {
+        match state.inner.next().await {
+            Some(Ok(token)) => {
+                state.acc.push_str(&token);
+                Some((Ok(token), state))
+            }
+            Some(Err(e)) => {
+                state.failed = true;
+                Some((Err(e), state))
+            }
+            None => {
+                if !state.failed {
+                    answer_cache::insert(state.key.clone(), state.acc.clone()).await;
+                }
+                None
+            }
+        }
+    }))
+}
+
+type BoxTokenStream =
+    std::pin::Pin<Box<dyn Stream<Item = anyhow::Result<String>> + Send>>;
+
+async fn generate_response_llm_stream(
+    messages: Vec<(String, String)>,
+    max_tokens: u32,
+) -> anyhow::Result<BoxTokenStream> {
+    // Streaming is wired to the OpenAI/Azure transport only. Honour an explicit
+    // `LLM_CLIENT_CONFIG` when it selects that backend and reject the Google
+    // backends loudly rather than silently falling through to OpenAI env vars.
+    let (api_url, api_key) = streaming_openai_endpoint()?;
+    let request = LLMRequest::builder()
+        .messages(messages)
+        .api_key(api_key)
+        .api_url(api_url)
+        .max_tokens(max_tokens)
+        .temperature(0.5)
+        .build();
+    let stream = request.send_stream().await?;
+    Ok(Box::pin(stream))
+}
+
+/// Resolve the OpenAI endpoint for streaming. A tagged `LLM_CLIENT_CONFIG` is
+/// honoured when it selects the OpenAI backend; the Gemini/Vertex backends have
+/// no streaming transport yet, so we error rather than ignore the config. With
+/// no config set we fall back to the historical OpenAI env vars.
+fn streaming_openai_endpoint() -> anyhow::Result<(String, String)> {
+    if let Ok(raw) = std::env::var("LLM_CLIENT_CONFIG") {
+        return match serde_json::from_str::<ClientConfig>(&raw)? {
+            ClientConfig::Openai { api_url, api_key } => Ok((api_url, api_key)),
+            ClientConfig::Gemini { .. } | ClientConfig::Vertexai { .. } => Err(anyhow::anyhow!(
+                "streaming /generate supports only the OpenAI backend; use /threads for this client config"
+            )),
+        };
+    }
+    let api_key = std::env::var("OPENAI_API_KEY").context("OPENAI_API_KEY not set")?;
+    let api_url = std::env::var("OPENAI_API_URL").context("OPENAI_API_URL not set")?;
+    Ok((api_url, api_key))
+}
+
+#[axum::debug_handler]
+async fn create_thread() -> impl IntoResponse {
+    let id = threads::create_thread().await;
+    info!("created thread {id}");
+    (StatusCode::CREATED, Json(serde_json::json!({ "thread_id": id })))
+}
+
+#[axum::debug_handler]
+async fn post_thread_message(Path(id): Path<u64>, body: String) -> impl IntoResponse {
+    info!("thread {id} received query: {body}");
+    match process_thread_query(id, body).await {
+        Ok(response) => (StatusCode::OK, response),
+        Err(e) => (llm_error::status_for(&e), e.to_string()),
+    }
+}
+
+/// Run a thread-scoped turn: record the user message, retrieve fresh context,
+/// assemble the full conversation history and ask the LLM, then persist the
+/// assistant reply so later turns resolve against it.
+async fn process_thread_query(id: u64, query: String) -> anyhow::Result<String> {
+    threads::append_message(id, "user", query.clone()).await?;
+
+    let embedding = embed_and_normalize_query(query.clone())?;
+    let context_tables = get_relevant_context_tables(embedding, 20).await?;
+
+    let prompt_msgs = assemble_thread_prompt(id, context_tables).await?;
+    let max_tokens = completion_max_tokens(&prompt_msgs);
+    let response = generate_response_llm(prompt_msgs, max_tokens).await?;
+
+    threads::append_message(id, "assistant", response.clone()).await?;
     Ok(response)
 }
 
+/// Build the message list for a thread turn: the system prompt and the prior
+/// conversation history, with the freshly retrieved schema context folded into
+/// the latest user turn. Appending the context to the existing query keeps the
+/// roles alternating, which the Gemini/Vertex backends require.
+async fn assemble_thread_prompt(
+    id: u64,
+    context_tables: Vec<TableContent>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    // Pack the highest-similarity tables up to the context budget, exactly as
+    // the stateless path does, so one oversized schema can't overflow the
+    // window on a thread turn either.
+    let (included, _) = pack_context_tables(context_tables);
+    let context_str = included.join("\n");
+
+    let context_block = format!(
+        r#"
+        Here are the tables in our database that may be relevant:
+        {context_str}
+        "#
+    );
+
+    let mut messages = vec![(String::from("system"), system_prompt())];
+    messages.extend(threads::history(id).await?);
+    match messages.last_mut() {
+        // The current query is the last history turn; graft the context onto it
+        // rather than adding a second consecutive user turn.
+        Some((role, content)) if role == "user" => {
+            content.push_str(&context_block);
+        }
+        _ => messages.push((String::from("user"), context_block)),
+    }
+    // Keep the assembled prompt inside the model window, leaving room for the
+    // answer, by dropping the oldest non-system turns as needed.
+    let budget = model_context_limit().saturating_sub(MIN_COMPLETION_TOKENS);
+    threads::trim_to_token_budget(&mut messages, budget);
+    Ok(messages)
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct TableContent {
     pub content: String,
@@ -123,41 +316,118 @@ async fn get_relevant_context_tables(
     Ok(table_contents)
 }
 
+/// The assembled prompt plus how many of the candidate tables actually made it
+/// in. Callers log the ratio so retrieval quality is observable on big schemas.
+pub struct PromptAssembly {
+    pub messages: Vec<(String, String)>,
+    pub included_tables: usize,
+    pub candidate_tables: usize,
+}
+
 async fn generate_prompt_msgs(
     query: String,
     context_tables: Vec<TableContent>,
-) -> anyhow::Result<Vec<(String, String)>> {
-    let context_str = context_tables
-        .iter()
-        .map(|t| t.content.clone())
-        .collect::<Vec<String>>()
-        .join("\n");
-    let prompt = create_prompt(context_str, query);
-    Ok(prompt)
-}
-
-async fn generate_response_llm(messages: Vec<(String, String)>) -> String {
-    let api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
-    let api_url = std::env::var("OPENAI_API_URL").expect("OPENAI_API_URL not set");
-    let request = LLMRequest::builder()
-        .messages(messages)
-        .api_key(api_key)
-        .api_url(api_url)
-        .max_tokens(800)
-        .temperature(0.5)
-        .build();
-    let response = request.send().await.expect("Failed to send request");
-    response
+) -> anyhow::Result<PromptAssembly> {
+    let (included, candidate_tables) = pack_context_tables(context_tables);
+    let included_tables = included.len();
+    let context_str = included.join("\n");
+    let messages = create_prompt(context_str, query);
+    Ok(PromptAssembly {
+        messages,
+        included_tables,
+        candidate_tables,
+    })
 }
 
-fn create_prompt(context: String, query: String) -> Vec<(String, String)> {
-    let sys = r#" 
-    You are an AI assistant that answers questions about database schemas and tables. 
-    Your answer always includes information about the relevant tables and their purpose. 
+/// Greedily pack the highest-similarity tables into the context budget. Tables
+/// arrive ordered by similarity; we keep taking them until the next block would
+/// blow `CONTEXT_TOKEN_BUDGET`, then drop the rest. The top table is always
+/// kept, even when it alone exceeds the budget. Returns the kept `content`
+/// blocks and the original candidate count so callers can log the ratio.
+fn pack_context_tables(context_tables: Vec<TableContent>) -> (Vec<String>, usize) {
+    let budget = context_token_budget();
+    let candidate_tables = context_tables.len();
+
+    let mut included = Vec::new();
+    let mut used = 0usize;
+    for table in context_tables {
+        let cost = tokenizer::estimate_tokens(&table.content);
+        if !included.is_empty() && used + cost > budget {
+            break;
+        }
+        used += cost;
+        included.push(table.content);
+    }
+    (included, candidate_tables)
+}
+
+fn context_token_budget() -> usize {
+    std::env::var("CONTEXT_TOKEN_BUDGET")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(6000)
+}
+
+fn model_context_limit() -> usize {
+    std::env::var("MODEL_CONTEXT_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8192)
+}
+
+/// Floor on the completion length so even a near-full prompt leaves room to
+/// answer; also the slice of the window reserved for the reply when trimming
+/// thread history down to the context limit.
+const MIN_COMPLETION_TOKENS: usize = 256;
+
+/// Budget the completion length as whatever remains of the model window after
+/// the prompt, with a small floor so a large prompt still leaves room to answer.
+fn completion_max_tokens(messages: &[(String, String)]) -> u32 {
+    let prompt_tokens = tokenizer::estimate_messages(messages);
+    model_context_limit()
+        .saturating_sub(prompt_tokens)
+        .max(MIN_COMPLETION_TOKENS) as u32
+}
+
+async fn generate_response_llm(
+    messages: Vec<(String, String)>,
+    max_tokens: u32,
+) -> anyhow::Result<String> {
+    let client = llm_client_from_env()?;
+    let opts = CompletionOpts {
+        max_tokens,
+        ..CompletionOpts::default()
+    };
+    client.complete(&messages, &opts).await
+}
+
+/// Resolve which backend to talk to. When `LLM_CLIENT_CONFIG` holds a tagged
+/// JSON config we honour it; otherwise we fall back to the historical OpenAI
+/// env vars so existing deployments keep working unchanged.
+fn llm_client_from_env() -> anyhow::Result<Box<dyn LlmClient>> {
+    if let Ok(raw) = std::env::var("LLM_CLIENT_CONFIG") {
+        let config: ClientConfig = serde_json::from_str(&raw)?;
+        return Ok(config.build());
+    }
+    let api_key = std::env::var("OPENAI_API_KEY").context("OPENAI_API_KEY not set")?;
+    let api_url = std::env::var("OPENAI_API_URL").context("OPENAI_API_URL not set")?;
+    Ok(ClientConfig::Openai { api_url, api_key }.build())
+}
+
+/// The shared system prompt driving the assistant's answer style, used by both
+/// the stateless and thread-scoped prompt builders.
+fn system_prompt() -> String {
+    r#"
+    You are an AI assistant that answers questions about database schemas and tables.
+    Your answer always includes information about the relevant tables and their purpose.
     When you add a query to your answer, always mark it with ```sql.
     Always use numbers when enumerating items.
     "#
-    .to_string();
+    .to_string()
+}
+
+fn create_prompt(context: String, query: String) -> Vec<(String, String)> {
+    let sys = system_prompt();
 
     let user = format!(
         r#"
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use once_cell::sync::Lazy;
+use tokio::sync::RwLock;
+
+/// Upper bound on stored turns per thread. The oldest non-system turns are
+/// dropped once a thread grows past this so the store stays bounded.
+const DEFAULT_MAX_MESSAGES: usize = 50;
+
+/// A conversation thread: an id and its ordered turns as `(role, content)`.
+pub struct Thread {
+    pub id: u64,
+    pub messages: Vec<(String, String)>,
+}
+
+static THREADS: Lazy<RwLock<HashMap<u64, Thread>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn max_messages() -> usize {
+    std::env::var("THREAD_MAX_MESSAGES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_MESSAGES)
+}
+
+/// Create an empty thread and return its id.
+pub async fn create_thread() -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    THREADS
+        .write()
+        .await
+        .insert(id, Thread { id, messages: Vec::new() });
+    id
+}
+
+/// Append a turn to a thread, enforcing the per-thread message limit by
+/// dropping the oldest non-system turns.
+pub async fn append_message(id: u64, role: &str, content: String) -> anyhow::Result<()> {
+    let mut threads = THREADS.write().await;
+    let thread = threads
+        .get_mut(&id)
+        .ok_or_else(|| anyhow::anyhow!("thread {id} not found"))?;
+    thread.messages.push((role.to_string(), content));
+    trim_to_limit(&mut thread.messages, max_messages());
+    Ok(())
+}
+
+/// Return a snapshot of a thread's ordered turns.
+pub async fn history(id: u64) -> anyhow::Result<Vec<(String, String)>> {
+    let threads = THREADS.read().await;
+    let thread = threads
+        .get(&id)
+        .ok_or_else(|| anyhow::anyhow!("thread {id} not found"))?;
+    Ok(thread.messages.clone())
+}
+
+/// Drop oldest non-system turns until the thread is within `limit` messages.
+fn trim_to_limit(messages: &mut Vec<(String, String)>, limit: usize) {
+    while messages.len() > limit {
+        if let Some(pos) = messages.iter().position(|(role, _)| role != "system") {
+            messages.remove(pos);
+        } else {
+            break;
+        }
+    }
+}
+
+/// Drop oldest non-system turns until the assembled prompt's estimated token
+/// count fits within `token_budget`. Keeps the system prompt and the most
+/// recent turns so a long thread degrades gracefully instead of overflowing the
+/// model window.
+pub fn trim_to_token_budget(messages: &mut Vec<(String, String)>, token_budget: usize) {
+    while crate::tokenizer::estimate_messages(messages) > token_budget {
+        if let Some(pos) = messages.iter().position(|(role, _)| role != "system") {
+            messages.remove(pos);
+        } else {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> (String, String) {
+        (role.to_string(), content.to_string())
+    }
+
+    #[test]
+    fn trim_to_limit_drops_oldest_non_system_turns() {
+        let mut messages = vec![
+            msg("system", "sys"),
+            msg("user", "one"),
+            msg("assistant", "two"),
+            msg("user", "three"),
+        ];
+        trim_to_limit(&mut messages, 3);
+        // The system turn is preserved and the oldest user turn is dropped.
+        assert_eq!(
+            messages,
+            vec![
+                msg("system", "sys"),
+                msg("assistant", "two"),
+                msg("user", "three"),
+            ]
+        );
+    }
+
+    #[test]
+    fn trim_to_limit_never_drops_the_system_turn() {
+        let mut messages = vec![msg("system", "a"), msg("system", "b")];
+        trim_to_limit(&mut messages, 1);
+        // Nothing non-system to drop, so the list is left untouched.
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn trim_to_token_budget_drops_until_within_budget() {
+        let mut messages = vec![
+            msg("system", "sys"),
+            msg("user", "a big old turn that costs several tokens"),
+            msg("user", "the latest turn"),
+        ];
+        let budget = crate::tokenizer::estimate_messages(&messages[..1])
+            + crate::tokenizer::estimate_tokens("user")
+            + crate::tokenizer::estimate_tokens("the latest turn");
+        trim_to_token_budget(&mut messages, budget);
+        assert_eq!(messages.first().map(|(r, _)| r.as_str()), Some("system"));
+        assert_eq!(messages.last().map(|(_, c)| c.as_str()), Some("the latest turn"));
+        assert!(crate::tokenizer::estimate_messages(&messages) <= budget);
+    }
+}
@@ -0,0 +1,53 @@
+//! A cheap, dependency-free token estimator. It is not a real BPE tokenizer —
+//! it approximates the count well enough to pack prompts without overflowing
+//! the model window, erring slightly high so we stay on the safe side.
+
+/// Estimate the number of tokens a string occupies. English text averages
+/// roughly four characters per token; we also never return fewer tokens than
+/// whitespace-separated words so short, punctuation-heavy inputs aren't
+/// under-counted.
+pub fn estimate_tokens(text: &str) -> usize {
+    let char_estimate = text.chars().count().div_ceil(4);
+    let word_estimate = text.split_whitespace().count();
+    char_estimate.max(word_estimate)
+}
+
+/// Sum the token estimate across an assembled message list.
+pub fn estimate_messages(messages: &[(String, String)]) -> usize {
+    messages
+        .iter()
+        .map(|(role, content)| estimate_tokens(role) + estimate_tokens(content))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_string_costs_nothing() {
+        assert_eq!(estimate_tokens(""), 0);
+    }
+
+    #[test]
+    fn char_estimate_rounds_up() {
+        // 13 characters, no spaces: ceil(13 / 4) = 4 dominates the word count.
+        assert_eq!(estimate_tokens("abcdefghijklm"), 4);
+    }
+
+    #[test]
+    fn word_count_is_a_floor_for_punctuation_heavy_text() {
+        // Five short punctuated words: char estimate (ceil(14/4) = 4) is below
+        // the word count, so the word count wins.
+        assert_eq!(estimate_tokens("a, b. c; d! e?"), 5);
+    }
+
+    #[test]
+    fn messages_sum_role_and_content() {
+        let messages = vec![("user".to_string(), "hello there".to_string())];
+        assert_eq!(
+            estimate_messages(&messages),
+            estimate_tokens("user") + estimate_tokens("hello there")
+        );
+    }
+}